@@ -0,0 +1,204 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use axum::extract::{FromRef, FromRequestParts, State};
+use axum::http::request::Parts;
+use axum::Json;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sqlx::error::DatabaseError;
+use sqlx::SqlitePool;
+
+use crate::{AppError, AppState};
+
+const TOKEN_TTL_SECS: i64 = 60 * 60 * 24 * 7;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    #[serde(skip_serializing)]
+    pub password_hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    sub: String,
+    username: String,
+    exp: i64,
+}
+
+/// The authenticated user, injected into handlers that require a valid
+/// `Authorization: Bearer <jwt>` header.
+#[derive(Debug, Clone)]
+pub struct JwtUser {
+    pub id: i64,
+    pub username: String,
+}
+
+impl<S> FromRequestParts<S> for JwtUser
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let app_state = AppState::from_ref(state);
+
+        let header = parts
+            .headers
+            .get(axum::http::header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| AppError::from("Missing Authorization header".to_string()))?;
+
+        let token = header
+            .strip_prefix("Bearer ")
+            .ok_or_else(|| AppError::from("Authorization header must be a Bearer token".to_string()))?;
+
+        let data = decode::<Claims>(
+            token,
+            &DecodingKey::from_secret(app_state.jwt_secret.as_bytes()),
+            &Validation::default(),
+        )
+        .map_err(|e| AppError::from(format!("Invalid token: {e}")))?;
+
+        let id = data
+            .claims
+            .sub
+            .parse::<i64>()
+            .map_err(|_| AppError::from("Invalid token subject".to_string()))?;
+
+        Ok(JwtUser {
+            id,
+            username: data.claims.username,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthResponse {
+    pub token: String,
+    pub user_id: i64,
+    pub username: String,
+}
+
+fn hash_password(password: &str) -> Result<String, AppError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| AppError::from(format!("Failed to hash password: {e}")))
+}
+
+fn verify_password(password: &str, hash: &str) -> Result<(), AppError> {
+    let parsed = PasswordHash::new(hash)
+        .map_err(|e| AppError::from(format!("Corrupt password hash: {e}")))?;
+    Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .map_err(|_| AppError::from("Invalid username or password".to_string()))
+}
+
+fn issue_token(user: &User, jwt_secret: &str) -> Result<String, AppError> {
+    let now = chrono::Utc::now().timestamp();
+    let claims = Claims {
+        sub: user.id.to_string(),
+        username: user.username.clone(),
+        exp: now + TOKEN_TTL_SECS,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(jwt_secret.as_bytes()),
+    )
+    .map_err(|e| AppError::from(format!("Failed to issue token: {e}")))
+}
+
+async fn insert_user(
+    pool: &SqlitePool,
+    username: &str,
+    password_hash: &str,
+) -> Result<User, sqlx::Error> {
+    let id = sqlx::query!(
+        r#"
+        INSERT INTO users (username, password_hash)
+        VALUES (?, ?)
+        "#,
+        username,
+        password_hash
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    Ok(User {
+        id,
+        username: username.to_string(),
+        password_hash: password_hash.to_string(),
+    })
+}
+
+async fn get_user_by_username(pool: &SqlitePool, username: &str) -> anyhow::Result<User> {
+    let user = sqlx::query_as!(
+        User,
+        r#"
+        SELECT id, username, password_hash FROM users WHERE username = ?
+        "#,
+        username
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn register(
+    State(state): State<AppState>,
+    Json(creds): Json<Credentials>,
+) -> Result<Json<AuthResponse>, AppError> {
+    let password_hash = hash_password(&creds.password)?;
+    let user = insert_user(&state.pool, &creds.username, &password_hash)
+        .await
+        .map_err(|e| match &e {
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                AppError::with_status(
+                    axum::http::StatusCode::CONFLICT,
+                    format!("Username {:?} is already taken", creds.username),
+                )
+            }
+            _ => AppError::from(e),
+        })?;
+    let token = issue_token(&user, &state.jwt_secret)?;
+
+    Ok(Json(AuthResponse {
+        token,
+        user_id: user.id,
+        username: user.username,
+    }))
+}
+
+pub async fn login(
+    State(state): State<AppState>,
+    Json(creds): Json<Credentials>,
+) -> Result<Json<AuthResponse>, AppError> {
+    let user = get_user_by_username(&state.pool, &creds.username)
+        .await
+        .map_err(|_| AppError::from("Invalid username or password".to_string()))?;
+
+    verify_password(&creds.password, &user.password_hash)?;
+
+    let token = issue_token(&user, &state.jwt_secret)?;
+
+    Ok(Json(AuthResponse {
+        token,
+        user_id: user.id,
+        username: user.username,
+    }))
+}
@@ -0,0 +1,152 @@
+use axum::extract::{Query, Request, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncBufReadExt;
+use tokio_util::io::StreamReader;
+
+use crate::auth::JwtUser;
+use crate::{cache, get_triple, AppError, AppState, Pair, Triple};
+
+/// Streams every triple as newline-delimited JSON so exporting a
+/// multi-million-row graph doesn't have to materialize it in memory first.
+pub async fn export(State(state): State<AppState>) -> Result<Response, AppError> {
+    let stream = sqlx::query_as!(
+        Triple,
+        r#"
+        SELECT t.a, t.b, t.c, t.discovered_by, t.discovered_at, u.username AS discovered_by_username
+        FROM triple t
+        LEFT JOIN users u ON u.id = t.discovered_by
+        "#
+    )
+    .fetch(&state.pool)
+    .map_ok(|triple| {
+        let mut line = serde_json::to_vec(&triple).expect("Triple is always serializable");
+        line.push(b'\n');
+        line
+    });
+
+    let body = axum::body::Body::from_stream(stream);
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        body,
+    )
+        .into_response())
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OnConflict {
+    #[default]
+    Skip,
+    Overwrite,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    on_conflict: OnConflict,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImportRow {
+    a: String,
+    b: String,
+    c: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportStats {
+    pub imported: u64,
+    pub skipped: u64,
+}
+
+/// Ingests the NDJSON format produced by `export`, canonicalizing each pair
+/// and either skipping or overwriting pairs that already exist. The request
+/// body is read and parsed line-by-line as it arrives, rather than buffered
+/// in full, so a multi-million-row import doesn't blow memory. Requires auth
+/// so that overwriting the graph isn't open to anonymous callers.
+pub async fn import(
+    State(state): State<AppState>,
+    Query(query): Query<ImportQuery>,
+    _user: JwtUser,
+    request: Request,
+) -> Result<Json<ImportStats>, AppError> {
+    let mut stats = ImportStats {
+        imported: 0,
+        skipped: 0,
+    };
+
+    let byte_stream = request
+        .into_body()
+        .into_data_stream()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e));
+    let mut lines = StreamReader::new(byte_stream).lines();
+
+    while let Some(line) = lines
+        .next_line()
+        .await
+        .map_err(|e| AppError::from(format!("Failed to read import body: {e}")))?
+    {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let row: ImportRow = serde_json::from_str(line)
+            .map_err(|e| AppError::from(format!("Invalid NDJSON line: {e}")))?;
+        let pair = Pair {
+            a: row.a,
+            b: row.b,
+        }
+        .canonical();
+
+        let rows_affected = match query.on_conflict {
+            OnConflict::Skip => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO triple (a, b, c)
+                    VALUES (?, ?, ?)
+                    ON CONFLICT(a, b) DO NOTHING
+                    "#,
+                    pair.a,
+                    pair.b,
+                    row.c
+                )
+                .execute(&state.pool)
+                .await?
+                .rows_affected()
+            }
+            OnConflict::Overwrite => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO triple (a, b, c)
+                    VALUES (?, ?, ?)
+                    ON CONFLICT(a, b) DO UPDATE SET c = excluded.c
+                    "#,
+                    pair.a,
+                    pair.b,
+                    row.c
+                )
+                .execute(&state.pool)
+                .await?
+                .rows_affected()
+            }
+        };
+
+        if rows_affected == 0 {
+            stats.skipped += 1;
+            continue;
+        }
+
+        stats.imported += 1;
+        if let Ok(triple) = get_triple(&state.pool, &pair.a, &pair.b).await {
+            cache::insert(&state.cache, triple).await;
+        }
+    }
+
+    Ok(Json(stats))
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::RwLock;
+
+use crate::{Pair, Triple};
+
+/// In-memory mirror of the `triple` table, keyed by canonical pair, so hot
+/// combinations can be served without a round-trip to SQLite.
+pub type Cache = Arc<RwLock<HashMap<Pair, Triple>>>;
+
+pub fn new() -> Cache {
+    Arc::new(RwLock::new(HashMap::new()))
+}
+
+pub async fn populate(cache: &Cache, triples: Vec<Triple>) {
+    let mut guard = cache.write().await;
+    for triple in triples {
+        let pair = Pair {
+            a: triple.a.clone(),
+            b: triple.b.clone(),
+        };
+        guard.insert(pair, triple);
+    }
+}
+
+pub async fn get(cache: &Cache, pair: &Pair) -> Option<Triple> {
+    cache.read().await.get(pair).cloned()
+}
+
+pub async fn insert(cache: &Cache, triple: Triple) {
+    let pair = Pair {
+        a: triple.a.clone(),
+        b: triple.b.clone(),
+    };
+    cache.write().await.insert(pair, triple);
+}
+
+pub async fn remove(cache: &Cache, pair: &Pair) {
+    cache.write().await.remove(pair);
+}
+
+/// Scans cached triples for ones touching `key`, mirroring what
+/// `find_triples` used to fetch from the database.
+pub async fn find_touching(cache: &Cache, key: &str, limit: usize) -> Vec<Triple> {
+    cache
+        .read()
+        .await
+        .values()
+        .filter(|t| t.a == key || t.b == key || t.c == key)
+        .take(limit)
+        .cloned()
+        .collect()
+}
@@ -1,5 +1,5 @@
 use anyhow::Context;
-use axum::extract::MatchedPath;
+use axum::extract::{DefaultBodyLimit, MatchedPath, Query};
 use axum::http::Request;
 use axum::routing::get;
 use axum::{extract::State, response::IntoResponse, routing::post, Json, Router};
@@ -8,14 +8,24 @@ use futures::future::join_all;
 use ollama_rs::generation::{completion::request::GenerationRequest, options::GenerationOptions};
 use ollama_rs::Ollama;
 use serde::{Deserialize, Serialize};
-use sqlx::sqlite::SqlitePool;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
 use tinytemplate::TinyTemplate;
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::CorsLayer;
+use tower_http::decompression::RequestDecompressionLayer;
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 use tracing::{error, info, Level};
-use tracing_subscriber::FmtSubscriber;
+
+mod auth;
+mod bulk;
+mod cache;
+mod votes;
+
+use auth::JwtUser;
 
 #[derive(Parser, Debug)]
 #[command(version, about = "World Graph", long_about = "World Graph")]
@@ -46,6 +56,28 @@ struct Args {
 
     #[arg(short, long, env, default_value = "info")]
     log_level: Level,
+
+    /// Secret used to sign and verify JWTs. Must be set to a stable value in production.
+    #[arg(long, env, default_value = "change-me")]
+    jwt_secret: String,
+
+    /// Allowed CORS origin. Use "*" to allow any origin.
+    #[arg(long, env, default_value = "*")]
+    cors_origin: String,
+
+    /// Net vote score at or below which a triple becomes eligible for /reroll.
+    #[arg(long, env, default_value = "-3")]
+    reroll_threshold: i64,
+
+    /// Max SQLite pool connections. Defaults to the available parallelism.
+    #[arg(long, env)]
+    max_connections: Option<u32>,
+
+    /// OTLP collector endpoint (e.g. http://localhost:4317) to export traces to.
+    /// Only available when built with the `otlp` feature.
+    #[cfg(feature = "otlp")]
+    #[arg(long, env)]
+    otlp_endpoint: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
@@ -54,14 +86,17 @@ enum StrategyChoice {
     Sample,
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, sqlx::FromRow, Serialize)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, sqlx::FromRow, Serialize)]
 struct Triple {
     a: String,
     b: String,
     c: String,
+    discovered_by: Option<i64>,
+    discovered_at: Option<String>,
+    discovered_by_username: Option<String>,
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Serialize, Deserialize)]
 struct Pair {
     a: String,
     b: String,
@@ -94,12 +129,27 @@ enum Strategy {
 
 #[derive(Debug, Serialize)]
 struct AppError {
+    #[serde(skip)]
+    status: axum::http::StatusCode,
     error: String,
 }
 
+impl AppError {
+    /// Builds an `AppError` with an explicit client-facing status code, for
+    /// cases that aren't an unexpected server failure (e.g. bad input or a
+    /// conflict with existing data).
+    fn with_status(status: axum::http::StatusCode, message: impl Into<String>) -> Self {
+        AppError {
+            status,
+            error: message.into(),
+        }
+    }
+}
+
 impl From<anyhow::Error> for AppError {
     fn from(e: anyhow::Error) -> Self {
         AppError {
+            status: axum::http::StatusCode::INTERNAL_SERVER_ERROR,
             error: e.to_string(),
         }
     }
@@ -108,6 +158,7 @@ impl From<anyhow::Error> for AppError {
 impl From<sqlx::Error> for AppError {
     fn from(e: sqlx::Error) -> Self {
         AppError {
+            status: axum::http::StatusCode::INTERNAL_SERVER_ERROR,
             error: e.to_string(),
         }
     }
@@ -116,6 +167,7 @@ impl From<sqlx::Error> for AppError {
 impl From<tinytemplate::error::Error> for AppError {
     fn from(e: tinytemplate::error::Error) -> Self {
         AppError {
+            status: axum::http::StatusCode::INTERNAL_SERVER_ERROR,
             error: e.to_string(),
         }
     }
@@ -123,26 +175,38 @@ impl From<tinytemplate::error::Error> for AppError {
 
 impl From<String> for AppError {
     fn from(e: String) -> Self {
-        AppError { error: e }
+        AppError {
+            status: axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            error: e,
+        }
     }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
         error!("{}", self.error);
-        (axum::http::StatusCode::INTERNAL_SERVER_ERROR, Json(&self)).into_response()
+        let status = self.status;
+        (status, Json(&self)).into_response()
     }
 }
 
-async fn insert_triple(pool: &SqlitePool, a: &str, b: &str, c: &str) -> anyhow::Result<()> {
+async fn insert_triple(
+    pool: &SqlitePool,
+    a: &str,
+    b: &str,
+    c: &str,
+    discovered_by: Option<i64>,
+) -> anyhow::Result<()> {
     let result = sqlx::query!(
         r#"
-        INSERT INTO triple (a, b, c)
-        VALUES (?, ?, ?)
+        INSERT INTO triple (a, b, c, discovered_by, discovered_at)
+        VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP)
+        ON CONFLICT(a, b) DO NOTHING
         "#,
         a,
         b,
-        c
+        c,
+        discovered_by
     )
     .execute(pool)
     .await;
@@ -163,7 +227,10 @@ async fn get_triple(pool: &SqlitePool, a: &str, b: &str) -> anyhow::Result<Tripl
     let result = sqlx::query_as!(
         Triple,
         r#"
-        SELECT a, b, c FROM triple WHERE a = ? AND b = ?
+        SELECT t.a, t.b, t.c, t.discovered_by, t.discovered_at, u.username AS discovered_by_username
+        FROM triple t
+        LEFT JOIN users u ON u.id = t.discovered_by
+        WHERE t.a = ? AND t.b = ?
         "#,
         a,
         b
@@ -178,23 +245,13 @@ async fn get_triple(pool: &SqlitePool, a: &str, b: &str) -> anyhow::Result<Tripl
 }
 
 async fn get_triples(pool: &SqlitePool) -> anyhow::Result<Vec<Triple>> {
-    let result = sqlx::query_as!(Triple, r#"SELECT a, b, c FROM triple"#)
-        .fetch_all(pool)
-        .await
-        .context("Failed to fetch from db")?;
-
-    Ok(result)
-}
-
-async fn find_triples(pool: &SqlitePool, a: &str) -> anyhow::Result<Vec<Triple>> {
     let result = sqlx::query_as!(
         Triple,
         r#"
-        SELECT a, b, c FROM triple WHERE a = ? OR b = ? OR c = ?
-        "#,
-        a,
-        a,
-        a
+        SELECT t.a, t.b, t.c, t.discovered_by, t.discovered_at, u.username AS discovered_by_username
+        FROM triple t
+        LEFT JOIN users u ON u.id = t.discovered_by
+        "#
     )
     .fetch_all(pool)
     .await
@@ -210,28 +267,49 @@ struct AppState {
     ollama_model: String,
     ollama_temperature: f32,
     strategy: Strategy,
+    jwt_secret: String,
+    reroll_threshold: i64,
+    cache: cache::Cache,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(args.log_level)
-        .finish();
-
-    tracing::subscriber::set_global_default(subscriber)
-        .context("setting default subscriber failed")?;
+    init_tracing(&args)?;
 
     info!("Ollama: {}:{}", args.ollama_host, args.ollama_port);
 
     let ollama = ollama_rs::Ollama::new(args.ollama_host, args.ollama_port);
 
-    info!("Sqlite: {}", args.sqlite);
-    let pool = SqlitePool::connect(&args.sqlite)
+    let max_connections = args.max_connections.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get() as u32)
+            .unwrap_or(4)
+    });
+
+    info!(
+        "Sqlite: {} (max_connections={})",
+        args.sqlite, max_connections
+    );
+
+    let connect_options = SqliteConnectOptions::from_str(&args.sqlite)
+        .context(format!("Invalid sqlite connection string {}", args.sqlite))?
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .busy_timeout(Duration::from_secs(5));
+
+    let pool = SqlitePoolOptions::new()
+        .max_connections(max_connections)
+        .connect_with(connect_options)
         .await
         .context(format!("Failed to connect to sqlite {}", args.sqlite))?;
 
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .context("Failed to run migrations")?;
+
     let socket = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), args.port);
 
     let listener = tokio::net::TcpListener::bind(socket)
@@ -245,45 +323,170 @@ async fn main() -> anyhow::Result<()> {
 
     info!("Strategy: {:?}", strategy);
 
+    let existing_triples = get_triples(&pool).await?;
+    info!("Cache warmed from {} existing triples", existing_triples.len());
+    let cache = cache::new();
+    cache::populate(&cache, existing_triples).await;
+
+    let shutdown_pool = pool.clone();
+
     let app_state = AppState {
         pool,
         ollama,
         ollama_model: args.ollama_model,
         ollama_temperature: args.ollama_temperature,
         strategy,
+        jwt_secret: args.jwt_secret,
+        reroll_threshold: args.reroll_threshold,
+        cache,
+    };
+
+    let cors = if args.cors_origin == "*" {
+        CorsLayer::permissive()
+    } else {
+        CorsLayer::new()
+            .allow_origin(
+                args.cors_origin
+                    .parse::<axum::http::HeaderValue>()
+                    .context("Invalid cors-origin")?,
+            )
+            .allow_methods([
+                axum::http::Method::GET,
+                axum::http::Method::POST,
+            ])
+            .allow_headers([
+                axum::http::header::AUTHORIZATION,
+                axum::http::header::CONTENT_TYPE,
+            ])
     };
 
     let app = Router::new()
         .nest_service("/", ServeDir::new("public"))
+        .route("/register", post(auth::register))
+        .route("/login", post(auth::login))
         .route("/wander", post(wander))
         .route("/explore", get(explore))
-        .layer(CorsLayer::permissive())
+        .route("/vote", post(votes::vote))
+        .route("/reroll", post(votes::reroll))
+        .route("/export", get(bulk::export))
+        .route(
+            "/import",
+            post(bulk::import).layer(DefaultBodyLimit::max(1024 * 1024 * 1024)),
+        )
+        .layer(CompressionLayer::new())
+        .layer(RequestDecompressionLayer::new())
+        .layer(cors)
         .layer(
-            TraceLayer::new_for_http().make_span_with(|request: &Request<_>| {
-                let matched_path = request
-                    .extensions()
-                    .get::<MatchedPath>()
-                    .map(MatchedPath::as_str);
-
-                tracing::info_span!(
-                    "http_request",
-                    method = ?request.method(),
-                    matched_path,
-                    some_other_field = tracing::field::Empty,
-                )
-            }),
+            TraceLayer::new_for_http()
+                .make_span_with(|request: &Request<_>| {
+                    let matched_path = request
+                        .extensions()
+                        .get::<MatchedPath>()
+                        .map(MatchedPath::as_str);
+
+                    tracing::info_span!(
+                        "http_request",
+                        method = ?request.method(),
+                        matched_path,
+                        some_other_field = tracing::field::Empty,
+                    )
+                })
+                .on_response(record_response_status),
         )
         .with_state(app_state);
 
     info!("Listening on {}", socket);
 
     axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
         .await
         .context("Failed to start server")?;
 
+    info!("Closing sqlite pool");
+    shutdown_pool.close().await;
+
     Ok(())
 }
 
+/// Waits for SIGINT or SIGTERM so in-flight `conjure` calls can finish their
+/// Ollama round-trips and commits before the pool is closed.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT, shutting down gracefully"),
+        _ = terminate => info!("Received SIGTERM, shutting down gracefully"),
+    }
+}
+
+fn record_response_status<B>(response: &axum::http::Response<B>, _latency: Duration, span: &tracing::Span) {
+    span.record("some_other_field", response.status().as_u16());
+}
+
+#[cfg(feature = "otlp")]
+fn init_tracing(args: &Args) -> anyhow::Result<()> {
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    let filter = tracing_subscriber::filter::LevelFilter::from_level(args.log_level);
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer());
+
+    match &args.otlp_endpoint {
+        Some(endpoint) => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint)
+                .build()
+                .context("Failed to build OTLP exporter")?;
+
+            let provider = opentelemetry_sdk::trace::TracerProvider::builder()
+                .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+                .build();
+
+            let tracer = opentelemetry::trace::TracerProvider::tracer(&provider, "world-graph");
+
+            registry
+                .with(tracing_opentelemetry::layer().with_tracer(tracer))
+                .try_init()
+                .context("setting default subscriber failed")
+        }
+        None => registry
+            .try_init()
+            .context("setting default subscriber failed"),
+    }
+}
+
+#[cfg(not(feature = "otlp"))]
+fn init_tracing(args: &Args) -> anyhow::Result<()> {
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    let filter = tracing_subscriber::filter::LevelFilter::from_level(args.log_level);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .try_init()
+        .context("setting default subscriber failed")
+}
+
 const UNDEF: &str = "undefined";
 
 // A prompt like this should work fairly well for both base and instruction tuned models.
@@ -362,17 +565,10 @@ fn prompt(a: &str, b: &str, examples: &str) -> Result<String, AppError> {
 }
 
 async fn get_examples(state: &AppState, pair: &Pair) -> Result<String, AppError> {
-    let tsa = find_triples(&state.pool, &pair.a)
-        .await?
-        .into_iter()
-        .take(5);
-
-    let tsb = find_triples(&state.pool, &pair.b)
-        .await?
-        .into_iter()
-        .take(5);
+    let tsa = cache::find_touching(&state.cache, &pair.a, 5).await;
+    let tsb = cache::find_touching(&state.cache, &pair.b, 5).await;
 
-    let mut merged = tsa.chain(tsb).collect::<Vec<Triple>>();
+    let mut merged = tsa.into_iter().chain(tsb).collect::<Vec<Triple>>();
     merged.dedup();
 
     Ok(merged
@@ -386,20 +582,36 @@ fn process_result(s: &str) -> String {
     s.trim().to_string()
 }
 
+#[tracing::instrument(skip_all, fields(pair_a = %pair.a, pair_b = %pair.b, source = tracing::field::Empty))]
 async fn wander(
     State(state): State<AppState>,
+    user: Option<JwtUser>,
     Json(pair): Json<Pair>,
 ) -> Result<Json<Triple>, AppError> {
     let pair = pair.canonical();
+    let span = tracing::Span::current();
+
+    if let Some(triple) = cache::get(&state.cache, &pair).await {
+        span.record("source", "cache");
+        return Ok(triple.into());
+    }
 
     let r = get_triple(&state.pool, &pair.a, &pair.b).await;
 
     match r {
-        Ok(triple) => Ok(triple.into()),
-        Err(_) => conjure(&state, &pair).await,
+        Ok(triple) => {
+            span.record("source", "db");
+            cache::insert(&state.cache, triple.clone()).await;
+            Ok(triple.into())
+        }
+        Err(_) => {
+            span.record("source", "llm");
+            conjure(&state, &pair, user.map(|u| u.id)).await
+        }
     }
 }
 
+#[tracing::instrument(skip_all, fields(model = %state.ollama_model, temperature = %state.ollama_temperature, latency_ms = tracing::field::Empty))]
 async fn completion(state: &AppState, p: &str) -> Result<String, AppError> {
     let req = GenerationRequest::new(state.ollama_model.to_string(), p.to_string())
         .options(
@@ -409,14 +621,24 @@ async fn completion(state: &AppState, p: &str) -> Result<String, AppError> {
         )
         .template("{{ .Prompt }}".to_string());
 
-    Ok(state
+    let start = std::time::Instant::now();
+    let result = state
         .ollama
         .generate(req.clone())
         .await
-        .map(|r| process_result(&r.response))?)
+        .map(|r| process_result(&r.response));
+
+    tracing::Span::current().record("latency_ms", start.elapsed().as_millis() as u64);
+
+    Ok(result?)
 }
 
-async fn conjure(state: &AppState, pair: &Pair) -> Result<Json<Triple>, AppError> {
+#[tracing::instrument(skip_all, fields(pair_a = %pair.a, pair_b = %pair.b, samples_requested = tracing::field::Empty, samples_returned = tracing::field::Empty))]
+async fn conjure(
+    state: &AppState,
+    pair: &Pair,
+    discovered_by: Option<i64>,
+) -> Result<Json<Triple>, AppError> {
     let examples = get_examples(state, pair).await?;
 
     let p = prompt(&pair.a, &pair.b, &examples)?;
@@ -424,21 +646,31 @@ async fn conjure(state: &AppState, pair: &Pair) -> Result<Json<Triple>, AppError
     let c = match state.strategy {
         Strategy::Simple => completion(&state, &p).await?,
         Strategy::Sample(n) => {
+            tracing::Span::current().record("samples_requested", n.saturating_sub(1));
+
             let gens = join_all((1..n).into_iter().map(|_| completion(&state, &p)))
                 .await
                 .into_iter()
                 .filter_map(Result::ok)
                 .collect::<Vec<String>>();
 
+            tracing::Span::current().record("samples_returned", gens.len());
+
             let mut counts = std::collections::HashMap::new();
 
             for g in gens {
                 *counts.entry(g).or_insert(0) += 1;
             }
 
-            counts
+            let mut scored = Vec::with_capacity(counts.len());
+            for (c, count) in counts {
+                let vote_score = votes::score_for(&state.pool, &pair.a, &pair.b, &c).await?;
+                scored.push((c, count + vote_score));
+            }
+
+            scored
                 .into_iter()
-                .max_by_key(|(_, count)| *count)
+                .max_by_key(|(_, score)| *score)
                 .map(|(c, _)| c)
                 .unwrap_or_else(|| {
                     error!("Empty samples!");
@@ -447,16 +679,29 @@ async fn conjure(state: &AppState, pair: &Pair) -> Result<Json<Triple>, AppError
         }
     };
 
-    let _ = insert_triple(&state.pool, &pair.a, &pair.b, &c).await?;
+    let _ = insert_triple(&state.pool, &pair.a, &pair.b, &c, discovered_by).await?;
 
-    Ok(Triple {
-        a: pair.a.clone(),
-        b: pair.b.clone(),
-        c,
-    }
-    .into())
+    let triple = get_triple(&state.pool, &pair.a, &pair.b).await?;
+    cache::insert(&state.cache, triple.clone()).await;
+
+    Ok(triple.into())
 }
 
-async fn explore(State(state): State<AppState>) -> Result<Json<Vec<Triple>>, AppError> {
-    Ok(Json(get_triples(&state.pool).await?))
+#[derive(Debug, Deserialize)]
+struct ExploreQuery {
+    #[serde(default)]
+    sort: Option<String>,
+}
+
+async fn explore(
+    State(state): State<AppState>,
+    Query(query): Query<ExploreQuery>,
+) -> Result<Json<Vec<Triple>>, AppError> {
+    let triples = if query.sort.as_deref() == Some("score") {
+        votes::triples_sorted_by_score(&state.pool).await?
+    } else {
+        get_triples(&state.pool).await?
+    };
+
+    Ok(Json(triples))
 }
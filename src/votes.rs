@@ -0,0 +1,125 @@
+use anyhow::Context;
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::auth::JwtUser;
+use crate::{conjure, AppError, AppState, Pair, Triple};
+
+#[derive(Debug, Deserialize)]
+pub struct VoteRequest {
+    pub a: String,
+    pub b: String,
+    pub c: String,
+    /// +1 to upvote, -1 to downvote.
+    pub vote: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VoteTally {
+    pub score: i64,
+}
+
+/// Net vote score (sum of +1/-1 votes) for an exact `a + b = c` answer.
+pub async fn score_for(pool: &SqlitePool, a: &str, b: &str, c: &str) -> anyhow::Result<i64> {
+    let score = sqlx::query_scalar!(
+        r#"
+        SELECT COALESCE(SUM(vote), 0) AS "score!: i64" FROM votes WHERE a = ? AND b = ? AND c = ?
+        "#,
+        a,
+        b,
+        c
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(score)
+}
+
+/// All triples, ordered by net vote score in a single aggregate query
+/// instead of one `score_for` round-trip per row.
+pub async fn triples_sorted_by_score(pool: &SqlitePool) -> anyhow::Result<Vec<Triple>> {
+    let result = sqlx::query_as!(
+        Triple,
+        r#"
+        SELECT t.a, t.b, t.c, t.discovered_by, t.discovered_at, u.username AS discovered_by_username
+        FROM triple t
+        LEFT JOIN users u ON u.id = t.discovered_by
+        LEFT JOIN (
+            SELECT a, b, c, SUM(vote) AS score FROM votes GROUP BY a, b, c
+        ) v ON v.a = t.a AND v.b = t.b AND v.c = t.c
+        ORDER BY COALESCE(v.score, 0) DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch from db")?;
+
+    Ok(result)
+}
+
+pub async fn vote(
+    State(state): State<AppState>,
+    user: JwtUser,
+    Json(req): Json<VoteRequest>,
+) -> Result<Json<VoteTally>, AppError> {
+    if req.vote != 1 && req.vote != -1 {
+        return Err(AppError::with_status(
+            axum::http::StatusCode::BAD_REQUEST,
+            "vote must be +1 or -1",
+        ));
+    }
+
+    let pair = Pair {
+        a: req.a,
+        b: req.b,
+    }
+    .canonical();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO votes (a, b, c, user_id, vote)
+        VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT(user_id, a, b, c) DO UPDATE SET vote = excluded.vote
+        "#,
+        pair.a,
+        pair.b,
+        req.c,
+        user.id,
+        req.vote
+    )
+    .execute(&state.pool)
+    .await?;
+
+    let score = score_for(&state.pool, &pair.a, &pair.b, &req.c).await?;
+
+    Ok(Json(VoteTally { score }))
+}
+
+pub async fn reroll(
+    State(state): State<AppState>,
+    _user: JwtUser,
+    Json(pair): Json<Pair>,
+) -> Result<Json<Triple>, AppError> {
+    let pair = pair.canonical();
+
+    let triple = crate::get_triple(&state.pool, &pair.a, &pair.b).await?;
+    let score = score_for(&state.pool, &pair.a, &pair.b, &triple.c).await?;
+
+    if score > state.reroll_threshold {
+        return Ok(triple.into());
+    }
+
+    sqlx::query!(
+        r#"DELETE FROM triple WHERE a = ? AND b = ?"#,
+        pair.a,
+        pair.b
+    )
+    .execute(&state.pool)
+    .await?;
+
+    crate::cache::remove(&state.cache, &pair).await;
+
+    conjure(&state, &pair, None).await
+}